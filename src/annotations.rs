@@ -0,0 +1,63 @@
+//! Parsing of compiletest's revision-scoped error-annotation comments (`//[rev]~ERROR`, `//~^`,
+//! `//~|`, `//~^^^^^`), as distinct from revision-scoped directive comments (`//[rev] name`).
+//!
+//! Both start with `//[rev]`, but an annotation's bracket is immediately followed by `~` with no
+//! intervening whitespace, while a directive's is followed by whitespace and a directive name —
+//! that's the only thing that tells the two apart.
+
+/// A parsed error-annotation line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AnnotationLine<'ln> {
+    /// The revision this annotation is scoped to, if any (`None` for a plain `//~...` line).
+    pub(crate) revision: Option<&'ln str>,
+}
+
+/// If `trimmed_line` is an error-annotation comment, returns its parsed form. Returns `None` for
+/// anything else, including revision-scoped directive comments like `//[rev] ignore-test` (no
+/// `~` immediately after the closing `]`).
+pub(crate) fn parse_annotation(trimmed_line: &str) -> Option<AnnotationLine<'_>> {
+    let rest = trimmed_line.strip_prefix("//")?;
+
+    if let Some(after_lbracket) = rest.strip_prefix('[') {
+        let rbracket_pos = after_lbracket.find(']')?;
+        let revision = &after_lbracket[..rbracket_pos];
+        let after_rbracket = &after_lbracket[(rbracket_pos + 1)..];
+        after_rbracket.starts_with('~').then_some(AnnotationLine { revision: Some(revision) })
+    } else {
+        rest.starts_with('~').then_some(AnnotationLine { revision: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_annotations() {
+        assert_eq!(parse_annotation("//~ERROR oops"), Some(AnnotationLine { revision: None }));
+        assert_eq!(parse_annotation("//~^"), Some(AnnotationLine { revision: None }));
+        assert_eq!(parse_annotation("//~|"), Some(AnnotationLine { revision: None }));
+        assert_eq!(parse_annotation("//~^^^^^"), Some(AnnotationLine { revision: None }));
+    }
+
+    #[test]
+    fn parses_revision_scoped_annotations() {
+        assert_eq!(
+            parse_annotation("//[foo]~ERROR oops"),
+            Some(AnnotationLine { revision: Some("foo") })
+        );
+    }
+
+    #[test]
+    fn rejects_revision_scoped_directives() {
+        // `//[rev] name` is a directive, not an annotation: no `~` right after the `]`.
+        assert_eq!(parse_annotation("//[foo] ignore-test"), None);
+    }
+
+    #[test]
+    fn rejects_non_annotation_comments() {
+        assert_eq!(parse_annotation("// just a comment"), None);
+        assert_eq!(parse_annotation("//@ ignore-test"), None);
+        assert_eq!(parse_annotation("let x = 1;"), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Discovery and collection of compiletest directive lines directly from a `rustc` checkout's
+//! `tests/` directory, so `migrate` and `collect-directive-names` don't have to depend on a
+//! pre-generated `__directive_lines.txt` from the upstream collection script.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+use crate::annotations::parse_annotation;
+use crate::directives::iter_directives;
+
+/// Walks `path_to_rustc/tests` and returns every compiletest test file (`.rs`/`.fixed`).
+///
+/// When `exclude_ui` is set, the `ui` test suite is skipped, since it has already been fully
+/// migrated to `//@` directives and `migrate` has no work left to do there.
+pub(crate) fn compiletest_test_file_paths(path_to_rustc: &Path, exclude_ui: bool) -> Vec<PathBuf> {
+    let ui_dir = path_to_rustc.join("tests").join("ui");
+
+    let walker = walkdir::WalkDir::new(path_to_rustc.join("tests"))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| {
+            !e.file_type().is_dir()
+                && e.path()
+                    .extension()
+                    .map(|s| s == "rs" || s == "fixed")
+                    .unwrap_or(false)
+                && !(exclude_ui && e.path().starts_with(&ui_dir))
+        })
+        .map(|e| e.into_path());
+
+    let mut test_file_paths = walker.collect::<Vec<_>>();
+    test_file_paths.sort();
+    test_file_paths
+}
+
+/// Returns `false` for lines that look like directive comments but aren't ones we care about:
+/// empty headers, empty comments, makefile-style `#` lines, and `ignore-tidy` hints.
+///
+/// Shared between the on-disk `__directive_lines.txt` loader and in-process collection so both
+/// apply the exact same filtering rules.
+pub(crate) fn is_meaningful_header(header: &str) -> bool {
+    let trimmed = header.trim();
+    !trimmed.is_empty() // skip empty header
+        && trimmed != "//" // skip empty comment
+        && !trimmed.starts_with('#') // skip makefile headers
+        && header
+            .split_once("//")
+            .map(|(_, post)| !post.trim().starts_with("ignore-tidy"))
+            .unwrap_or(true)
+}
+
+/// Walks `tests/` directly and parses every directive comment with [`iter_directives`], instead
+/// of reading a pre-generated `__directive_lines.txt` from the upstream collection script.
+pub(crate) fn collect_headers_in_process(path_to_rustc: &Path) -> anyhow::Result<BTreeSet<String>> {
+    assert!(path_to_rustc.exists(), "$PATH_TO_RUSTC_REPO does not exist");
+
+    let test_file_paths = compiletest_test_file_paths(path_to_rustc, true);
+    debug!("walking {} compiletest test files", test_file_paths.len());
+
+    let mut collected_headers = BTreeSet::new();
+    for path in &test_file_paths {
+        let contents = std::fs::read_to_string(path)?;
+        iter_directives(&contents, &mut |directive| {
+            // Error annotations (`//~...`, `//[rev]~...`) parse as directive-shaped but aren't
+            // directives; the upstream collection script never emits them, so skip them here too
+            // to keep the two collection modes in agreement.
+            if parse_annotation(directive.raw_line.trim()).is_some() {
+                return;
+            }
+            collected_headers.insert(directive.raw_line.trim().to_owned());
+        });
+    }
+
+    collected_headers.retain(|header| is_meaningful_header(header));
+
+    debug!("collected {} headers in-process", collected_headers.len());
+
+    Ok(collected_headers)
+}
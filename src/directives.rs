@@ -0,0 +1,219 @@
+//! A small parser for compiletest directive comments, modeled on compiletest's own
+//! `iter_header`/header-parsing helpers. `migrate` and `collect-directive-names` both need to
+//! know, for an arbitrary line of a test file, whether it is a directive comment and if so what
+//! its revision/name/value are; this module is the single place that answers that question so
+//! the two subcommands can't drift out of sync on what counts as a directive.
+
+use std::collections::BTreeSet;
+
+use tracing::debug;
+
+/// A single directive comment line parsed out of a compiletest test file.
+///
+/// Covers both the legacy `// name: value` style and the migrated `//@ name: value` style, along
+/// with the optional `//[rev]` / `//@[rev]` revision prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DirectiveLine<'ln> {
+    /// 1-based line number within the file this directive was found on.
+    pub(crate) line_number: usize,
+    /// The revision this directive is scoped to, e.g. `Some("foo")` for `//[foo] ignore-test`.
+    pub(crate) revision: Option<&'ln str>,
+    /// The directive name, e.g. `ignore-test`.
+    pub(crate) directive_name: &'ln str,
+    /// The directive's value, if any, e.g. `windows` in `//@ ignore-test: windows`.
+    pub(crate) value: Option<&'ln str>,
+    /// The full, unmodified source line (without its line ending).
+    pub(crate) raw_line: &'ln str,
+}
+
+/// Iterates over every directive comment line in `contents`, invoking `f` with the parsed
+/// [`DirectiveLine`] for each one. Lines that are not comments (don't start with `//` once
+/// trimmed) are silently skipped.
+///
+/// # Panics
+///
+/// Panics (reporting the offending line number) if a `[` revision marker is not closed by a
+/// matching `]` on the same line, since such a file is too malformed to migrate safely.
+pub(crate) fn iter_directives(contents: &str, f: &mut dyn FnMut(DirectiveLine<'_>)) {
+    for (idx, raw_line) in contents.lines().enumerate() {
+        let line_number = idx + 1;
+        let trimmed = raw_line.trim();
+
+        let Some(rest) = trimmed.strip_prefix("//") else {
+            continue;
+        };
+        // Accept both the legacy `//` sigil and the migrated `//@` sigil.
+        let rest = rest.strip_prefix('@').unwrap_or(rest).trim_start();
+
+        let (revision, rest) = if let Some(after_lbracket) = rest.strip_prefix('[') {
+            let Some(rbracket_pos) = after_lbracket.find(']') else {
+                panic!("line {line_number}: unpaired `[` in directive line: `{raw_line}`");
+            };
+            let revision = &after_lbracket[..rbracket_pos];
+            (Some(revision), after_lbracket[(rbracket_pos + 1)..].trim_start())
+        } else {
+            (None, rest)
+        };
+
+        // Special case: some test files have weird syntax like `// [rev]: directive-name`, so
+        // skip that pesky stray colon before splitting out the directive name.
+        let rest = rest.strip_prefix(':').map_or(rest, str::trim_start);
+
+        if rest.is_empty() {
+            // `//` or `//@` on its own, or only a `[rev]` marker: not a directive.
+            continue;
+        }
+
+        let (directive_name, value) = match rest.split_once([':', ' ']) {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (rest.trim(), None),
+        };
+
+        if directive_name.is_empty() {
+            continue;
+        }
+
+        debug!(line_number, ?revision, directive_name, ?value, "parsed directive line");
+
+        f(DirectiveLine { line_number, revision, directive_name, value, raw_line });
+    }
+}
+
+/// Parses the `//@ revisions: a b c` directive (if any) out of a test file's contents and
+/// returns the set of declared revision names. Shared by the `migrate` annotation checks and the
+/// `check-stale-outputs` subcommand, since both need to know what revisions a file declares.
+pub(crate) fn declared_revisions(contents: &str) -> BTreeSet<String> {
+    let mut revisions = BTreeSet::new();
+    iter_directives(contents, &mut |directive| {
+        if directive.directive_name == "revisions" {
+            if let Some(value) = directive.value {
+                revisions.extend(value.split_whitespace().map(ToOwned::to_owned));
+            }
+        }
+    });
+    revisions
+}
+
+/// Returns whether `name` is shaped like a real compiletest directive name (lowercase ASCII
+/// letters, digits, hyphens, and underscores only), as opposed to the first "word" of a plain prose comment
+/// (e.g. `// See issue 123` parses via [`iter_directives`] as a directive named `See`).
+/// `iter_directives` has no allowlist of its own, so callers that want to report on directive-
+/// shaped lines specifically (rather than every `//` comment) should filter through this first.
+pub(crate) fn looks_like_directive_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Replaces whole-word occurrences of `old` with `new` in `text`, preserving everything else
+/// (including whitespace) verbatim. Used to rename a revision within a `//@ revisions: ...`
+/// value list in place.
+pub(crate) fn rename_word(text: &str, old: &str, new: &str) -> String {
+    text.split_inclusive(char::is_whitespace)
+        .map(|token| {
+            let word_len = token.trim_end_matches(char::is_whitespace).len();
+            let (word, trailing_ws) = token.split_at(word_len);
+            if word == old { format!("{new}{trailing_ws}") } else { token.to_owned() }
+        })
+        .collect()
+}
+
+/// Collapses a line down to a canonical form: leading/trailing whitespace trimmed and every run
+/// of internal whitespace reduced to a single space. Used to compare a test file's directive line
+/// against the collected header set by *content* rather than by directive name alone, so a line
+/// only differing in spacing or trailing whitespace from a collected header still counts as a
+/// match, while a prose comment that merely starts with a directive-shaped word does not.
+pub(crate) fn normalize_header(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_directives(contents: &str) -> Vec<(Option<&str>, &str, Option<&str>)> {
+        let mut found = Vec::new();
+        iter_directives(contents, &mut |d| found.push((d.revision, d.directive_name, d.value)));
+        found
+    }
+
+    #[test]
+    fn parses_legacy_and_migrated_sigils() {
+        assert_eq!(collect_directives("// ignore-test"), vec![(None, "ignore-test", None)]);
+        assert_eq!(collect_directives("//@ ignore-test"), vec![(None, "ignore-test", None)]);
+    }
+
+    #[test]
+    fn parses_name_value_directives() {
+        assert_eq!(
+            collect_directives("//@ only-x86_64: windows"),
+            vec![(None, "only-x86_64", Some("windows"))]
+        );
+        assert_eq!(
+            collect_directives("// revisions: a b c"),
+            vec![(None, "revisions", Some("a b c"))]
+        );
+    }
+
+    #[test]
+    fn parses_revision_scoped_directives() {
+        assert_eq!(
+            collect_directives("//[foo] ignore-test"),
+            vec![(Some("foo"), "ignore-test", None)]
+        );
+        assert_eq!(
+            collect_directives("//@[foo] ignore-test: windows"),
+            vec![(Some("foo"), "ignore-test", Some("windows"))]
+        );
+    }
+
+    #[test]
+    fn handles_stray_colon_after_revision_bracket() {
+        assert_eq!(
+            collect_directives("// [foo]: ignore-test"),
+            vec![(Some("foo"), "ignore-test", None)]
+        );
+    }
+
+    #[test]
+    fn skips_non_directive_lines() {
+        assert_eq!(collect_directives("let x = 1;"), vec![]);
+        assert_eq!(collect_directives("//"), vec![]);
+        assert_eq!(collect_directives("//@"), vec![]);
+        assert_eq!(collect_directives("//[foo]"), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unpaired `[`")]
+    fn unpaired_bracket_panics() {
+        collect_directives("//[foo ignore-test");
+    }
+
+    #[test]
+    fn declared_revisions_collects_all_names() {
+        let contents = "//@ revisions: a b\n//@ ignore-test\n//@ revisions: c\n";
+        let revisions: Vec<_> = declared_revisions(contents).into_iter().collect();
+        assert_eq!(revisions, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn looks_like_directive_name_accepts_directive_shapes_only() {
+        for name in ["ignore-test", "only-x86_64", "edition2021"] {
+            assert!(looks_like_directive_name(name), "{name} should look like a directive");
+        }
+        for name in ["See", "Foo-Bar", "", "has space"] {
+            assert!(!looks_like_directive_name(name), "{name} should not look like a directive");
+        }
+    }
+
+    #[test]
+    fn rename_word_only_replaces_whole_words() {
+        assert_eq!(rename_word("foo bar foobar foo", "foo", "baz"), "baz bar foobar baz");
+        assert_eq!(rename_word("foo,bar", "foo", "baz"), "foo,bar");
+        assert_eq!(rename_word("  foo  \tfoo\n", "foo", "baz"), "  baz  \tbaz\n");
+    }
+
+    #[test]
+    fn normalize_header_collapses_whitespace() {
+        assert_eq!(normalize_header("  //@  ignore-test  \t"), "//@ ignore-test");
+        assert_eq!(normalize_header("//@ignore-test"), "//@ignore-test");
+    }
+}
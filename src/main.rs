@@ -1,16 +1,29 @@
-#![feature(let_chains)]
-
 use std::collections::BTreeSet;
-use std::io::{BufRead, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use anyhow::{bail, Context};
 use clap::{Parser, Subcommand};
 use confique::{toml::FormatOptions, Config as ConfigParser};
-use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 use tracing::*;
 
+use annotations::parse_annotation;
+use collect::{collect_headers_in_process, compiletest_test_file_paths, is_meaningful_header};
+use directives::{
+    declared_revisions, iter_directives, looks_like_directive_name, normalize_header, rename_word,
+};
+use report::MigrationReport;
+use stale_outputs::check_stale_outputs;
+
+mod annotations;
+mod collect;
+mod directives;
 mod logging;
+mod report;
+mod stale_outputs;
 
 const TARGET: &str = env!("TARGET");
 
@@ -32,6 +45,11 @@ pub(crate) enum Command {
         /// generated by a test directive collection script beforehand.
         #[clap(value_name = "PATH_TO_RUSTC")]
         path_to_rustc: PathBuf,
+        /// Rename a revision in the same pass: renames it in each file's `//@ revisions: ...`
+        /// list and in every `//[old]~` / `//[old] directive` occurrence, so directive renames
+        /// and their error annotations don't drift apart. Format: `<OLD>:<NEW>`.
+        #[clap(long, value_name = "OLD:NEW")]
+        rename_revision: Option<String>,
     },
     /// From the collected headers from `rustc` repo generated by the collection tool, output
     /// a Rust array consisting of directive names (does not include revisions or values or
@@ -42,6 +60,23 @@ pub(crate) enum Command {
         #[clap(value_name = "PATH_TO_RUSTC")]
         path_to_rustc: PathBuf,
     },
+    /// Walk `tests/` in the `rustc` repo directly and collect directive lines in-process,
+    /// printing one per line. This never reads a pre-generated `__directive_lines.txt` from the
+    /// upstream collection script (which `migrate`/`collect-directive-names` read unless
+    /// `collect_headers_in_process` is set in the config).
+    Collect {
+        #[clap(value_name = "PATH_TO_RUSTC")]
+        path_to_rustc: PathBuf,
+    },
+    /// Flag (and optionally delete) `.stderr`/`.stdout` expected-output files that reference a
+    /// revision the corresponding test no longer declares via `//@ revisions: ...`.
+    CheckStaleOutputs {
+        #[clap(value_name = "PATH_TO_RUSTC")]
+        path_to_rustc: PathBuf,
+        /// Delete orphaned expected-output files instead of only reporting them.
+        #[clap(long)]
+        fix: bool,
+    },
 }
 
 #[derive(Debug, Default, ConfigParser)]
@@ -50,6 +85,10 @@ pub(crate) struct Config {
     /// not properly handled by the collection script.
     #[config(default = [])]
     pub(crate) manual_directives: Vec<String>,
+    /// Collect directive lines by walking `tests/` in-process instead of reading the on-disk
+    /// `__directive_lines.txt` produced by the upstream collection script.
+    #[config(default = false)]
+    pub(crate) collect_headers_in_process: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -76,22 +115,57 @@ fn main() -> anyhow::Result<()> {
                 bail!("migration_config.toml already exists!");
             }
         }
-        Command::Migrate { path_to_rustc } => {
-            let mut collected_headers = collect_headers(path_to_rustc.as_path())?;
-            collected_headers.extend(config.manual_directives);
-            migrate_compiletest_tests(path_to_rustc.as_path(), &collected_headers)?;
+        Command::Migrate { path_to_rustc, rename_revision } => {
+            let collected_headers = load_collected_headers(path_to_rustc.as_path(), &config)?;
+            let normalized_headers: BTreeSet<String> =
+                collected_headers.iter().map(|header| normalize_header(header)).collect();
+            let rename_revision = rename_revision
+                .as_deref()
+                .map(|spec| {
+                    spec.split_once(':').with_context(|| {
+                        format!("`--rename-revision` expects `<OLD>:<NEW>`, got `{spec}`")
+                    })
+                })
+                .transpose()?;
+            migrate_compiletest_tests(
+                path_to_rustc.as_path(),
+                &normalized_headers,
+                rename_revision,
+            )?;
         }
         Command::CollectDirectiveNames { path_to_rustc } => {
-            let mut collected_headers = collect_headers(path_to_rustc.as_path())?;
-            collected_headers.extend(config.manual_directives);
+            let collected_headers = load_collected_headers(path_to_rustc.as_path(), &config)?;
             let directive_names = extract_directive_names(&collected_headers)?;
             println!("{:?}", directive_names.iter().collect::<Vec<_>>());
         }
+        Command::Collect { path_to_rustc } => {
+            let collected_headers = collect_headers_in_process(path_to_rustc.as_path())?;
+            for header in &collected_headers {
+                println!("{header}");
+            }
+        }
+        Command::CheckStaleOutputs { path_to_rustc, fix } => {
+            check_stale_outputs(path_to_rustc.as_path(), *fix)?;
+        }
     }
 
     Ok(())
 }
 
+/// Loads the set of collected directive lines, either from the on-disk
+/// `__directive_lines.txt` produced by the upstream collection script or by walking `tests/`
+/// in-process, depending on `config.collect_headers_in_process`, and merges in any
+/// `manual_directives` from the config.
+fn load_collected_headers(path_to_rustc: &Path, config: &Config) -> anyhow::Result<BTreeSet<String>> {
+    let mut collected_headers = if config.collect_headers_in_process {
+        collect_headers_in_process(path_to_rustc)?
+    } else {
+        collect_headers(path_to_rustc)?
+    };
+    collected_headers.extend(config.manual_directives.iter().cloned());
+    Ok(collected_headers)
+}
+
 fn collect_headers(path_to_rustc: &Path) -> anyhow::Result<BTreeSet<String>> {
     debug!(?path_to_rustc);
     assert!(path_to_rustc.exists(), "$PATH_TO_RUSTC_REPO does not exist");
@@ -112,14 +186,7 @@ fn collect_headers(path_to_rustc: &Path) -> anyhow::Result<BTreeSet<String>> {
         .map(ToOwned::to_owned)
         .collect::<BTreeSet<String>>();
 
-    collected_headers.retain(|header| {
-        !header.trim().is_empty() // skip empty header
-        && header.trim() != "//" // skip empty comment
-        && !header.trim().starts_with('#') // skip makefile headers
-        && header.split_once("//").map(|(_, post)| {
-            !post.trim().starts_with("ignore-tidy")
-        }).unwrap_or(true)
-    });
+    collected_headers.retain(|header| is_meaningful_header(header));
 
     info!("there are {} collected headers", collected_headers.len());
 
@@ -128,26 +195,10 @@ fn collect_headers(path_to_rustc: &Path) -> anyhow::Result<BTreeSet<String>> {
 
 fn migrate_compiletest_tests(
     path_to_rustc: &Path,
-    collected_headers: &BTreeSet<String>,
+    normalized_headers: &BTreeSet<String>,
+    rename_revision: Option<(&str, &str)>,
 ) -> anyhow::Result<()> {
-    // Collect paths of compiletest test files
-    let walker = walkdir::WalkDir::new(path_to_rustc.join("tests"))
-        .sort_by_file_name()
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| {
-            !e.file_type().is_dir()
-                && e.path()
-                    .extension()
-                    .map(|s| s == "rs" || s == "fixed")
-                    .unwrap_or(false)
-                // We already migrated ui test suite tests
-                && !e.path().starts_with(path_to_rustc.join("tests").join("ui"))
-        })
-        .map(|e| e.into_path());
-
-    let mut test_file_paths = walker.collect::<Vec<_>>();
-    test_file_paths.sort();
+    let test_file_paths = compiletest_test_file_paths(path_to_rustc, true);
 
     info!("there are {} compiletest test files", test_file_paths.len());
 
@@ -159,49 +210,129 @@ fn migrate_compiletest_tests(
         .unwrap(),
     );
 
-    for path in test_file_paths.iter().progress_with(pb) {
-        debug!(?path, "processing file");
-        // - Read the contents of the compiletest test file
-        // - Open a named temporary file
-        // - Process each line of the compiletest test:
-        //     - If line starts with "//", try to match it with one of the collected directives.
-        //       If a match is found, replace "//" with "//@" and append line to temp file.
-        //     - Otherwise, append line verbatim to temp file.
-        // - Replace original compiletest test with temp file.
-        let compiletest_test_file = std::fs::File::open(&path)?;
-        let mut reader = std::io::BufReader::new(compiletest_test_file);
-
-        let mut tmp_file = tempfile::NamedTempFile::new()?;
-
-        let mut line_buf = String::new();
-        'line: loop {
-            line_buf.clear();
-            let bytes_read = reader.read_line(&mut line_buf)?;
-            if bytes_read == 0 {
-                break;
-            }
+    let processed = AtomicU64::new(0);
+    let report = MigrationReport::default();
 
-            if line_buf.trim_start().starts_with("//") {
-                let (before, after) = line_buf.split_once("//").unwrap();
+    test_file_paths.par_iter().try_for_each(|path| -> anyhow::Result<()> {
+        migrate_one_file(path, normalized_headers, rename_revision, &report)?;
+        pb.set_position(processed.fetch_add(1, Ordering::Relaxed) + 1);
+        Ok(())
+    })?;
 
-                for header in collected_headers.iter() {
-                    if line_buf.replace("\r", "").replace("\n", "") == *header {
-                        write!(tmp_file, "{}//@{}", before, after)?;
-                        continue 'line;
+    pb.finish_and_clear();
+    report.print_summary();
+
+    Ok(())
+}
+
+/// Migrates a single compiletest test file: rewrites every `//` directive line whose
+/// whitespace-normalized content is in `normalized_headers` to `//@`, validates every
+/// `//[rev]~...` error annotation's revision against the file's declared `//@ revisions: ...`
+/// list, and, if `rename_revision` is set, renames that revision everywhere in the file (the
+/// revisions list, directive brackets, and annotation brackets alike) in the same pass. Runs on a
+/// rayon worker thread, so all shared state goes through `report`.
+///
+/// Matching on the full (normalized) line rather than just the parsed directive name keeps a
+/// prose comment that merely starts with a directive-shaped word (e.g. `// revisions of this
+/// bug`) from being mistaken for a real `revisions` directive and rewritten.
+fn migrate_one_file(
+    path: &Path,
+    normalized_headers: &BTreeSet<String>,
+    rename_revision: Option<(&str, &str)>,
+    report: &MigrationReport,
+) -> anyhow::Result<()> {
+    debug!(?path, "processing file");
+
+    let contents = std::fs::read_to_string(path)?;
+    let revisions = declared_revisions(&contents);
+
+    let mut output = String::with_capacity(contents.len());
+    for (idx, raw_line) in contents.split_inclusive('\n').enumerate() {
+        let line_number = idx + 1;
+        let trimmed = raw_line.trim();
+
+        // Error annotations (`//~...`, `//[rev]~...`) are not directives; handle them on their
+        // own so they don't get mistaken for an unrecognized directive in the report below.
+        if let Some(annotation) = parse_annotation(trimmed) {
+            let mut line = raw_line.to_owned();
+
+            if let Some(revision) = annotation.revision {
+                if !revisions.contains(revision) {
+                    report.record_undeclared_annotation_revision(
+                        path.to_owned(),
+                        line_number,
+                        revision,
+                    );
+                }
+                if let Some((old, new)) = rename_revision {
+                    if revision == old {
+                        line = line.replacen(&format!("[{old}]"), &format!("[{new}]"), 1);
                     }
                 }
+            }
 
-                // No matched directive, very unlikely a directive and instead just a comment
-                write!(tmp_file, "{}", line_buf)?;
-            } else {
-                write!(tmp_file, "{}", line_buf)?;
+            output.push_str(&line);
+            continue;
+        }
+
+        // Only lines not already using the `//@` sigil are migration candidates.
+        let mut matched_directive_name = None;
+        let mut unmatched_directive_name = None;
+        let mut directive_revision = None;
+        let mut is_revisions_directive = false;
+        if !trimmed.starts_with("//@") {
+            let normalized_line = normalize_header(trimmed);
+            iter_directives(raw_line, &mut |directive| {
+                directive_revision = directive.revision.map(ToOwned::to_owned);
+                is_revisions_directive = directive.directive_name == "revisions";
+                if normalized_headers.contains(&normalized_line) {
+                    matched_directive_name = Some(directive.directive_name.to_owned());
+                } else if looks_like_directive_name(directive.directive_name) {
+                    // Only report lines that are actually shaped like a directive; plain prose
+                    // comments (e.g. `// See issue 123`) parse as directive-shaped too but aren't,
+                    // and would otherwise bury real signal in the report.
+                    unmatched_directive_name = Some(directive.directive_name.to_owned());
+                }
+            });
+        } else {
+            // Already-migrated lines aren't rewritten, but are still parsed so `rename_revision`
+            // reaches their revision brackets and `revisions` lists.
+            iter_directives(raw_line, &mut |directive| {
+                directive_revision = directive.revision.map(ToOwned::to_owned);
+                is_revisions_directive = directive.directive_name == "revisions";
+            });
+        }
+
+        let mut line = if let Some(directive_name) = &matched_directive_name {
+            // `split_once` rather than the parsed directive is used here since we need to
+            // preserve the line's exact leading whitespace and trailing newline verbatim.
+            let (before, after) = raw_line.split_once("//").unwrap();
+            report.record_rewrite(path.to_owned(), line_number, directive_name);
+            format!("{before}//@{after}")
+        } else {
+            if let Some(directive_name) = &unmatched_directive_name {
+                report.record_unmatched(path.to_owned(), line_number, directive_name);
+            }
+            raw_line.to_owned()
+        };
+
+        if let Some((old, new)) = rename_revision {
+            if directive_revision.as_deref() == Some(old) {
+                line = line.replacen(&format!("[{old}]"), &format!("[{new}]"), 1);
+            }
+            if is_revisions_directive {
+                line = rename_word(&line, old, new);
             }
         }
 
-        let tmp_path = tmp_file.into_temp_path();
-        tmp_path.persist(path)?;
+        output.push_str(&line);
     }
 
+    let mut tmp_file = tempfile::NamedTempFile::new()?;
+    tmp_file.write_all(output.as_bytes())?;
+    let tmp_path = tmp_file.into_temp_path();
+    tmp_path.persist(path)?;
+
     Ok(())
 }
 
@@ -211,72 +342,16 @@ fn extract_directive_names(
     let mut ret = BTreeSet::new();
 
     for raw_directive in collected_directives {
-        // Directives can take the forms:
-        // 1. `// name` or with value or with comments:
-        //     - `// name: <rest>`
-        //     - `// name <rest>`
-        // 2. `//[rev] name` or with value or with commments:
-        //     - `//[rev] name: ...`
-        //     - `//[rev] name ...`
-        // There may be arbitrary whitespace between `//`, `[rev]` and `name`.
-
-        // First, let's get rid of the `//`.
-        let Some((leading, rest)) = raw_directive.split_once("//") else {
-            bail!("failed to split `{}`", raw_directive);
-        };
-        assert!(
-            leading.trim().is_empty(),
-            "expected directive to be leading in the line, there's a bug in the collection script"
-        );
-        let rest = rest.trim_start();
-
-        // Next, let's get rid of revisions.
-        let mut rest = if let Some(lbracket_pos) = rest.find('[')
-            && rest.starts_with('[')
-        {
-            let Some(rbracket_pos) = rest.find(']') else {
-                error!(
-                    ?raw_directive,
-                    ?lbracket_pos,
-                    "weird directive: `{:?}`",
-                    rest
-                );
-                panic!("directive found with unpaired [] delimiters");
-            };
-            if lbracket_pos > rbracket_pos {
-                error!(
-                    ?raw_directive,
-                    ?lbracket_pos,
-                    ?rbracket_pos,
-                    "weird directive: `{:?}`",
-                    rest
-                );
-            }
-            assert!(lbracket_pos <= rbracket_pos);
-            let rest = &rest[(rbracket_pos + 1)..];
-            rest.trim_start()
-        } else {
-            rest.trim_start()
-        };
-
-        // Special case: one of the test files has some weird syntax like
-        // `// [rev]: directive-name`...
-        if rest.starts_with(':') {
-            // ... so skip that pesky colon.
-            rest = &rest[1..];
-            rest = rest.trim_start();
+        // Each collected entry is a single directive line on its own; feed it through the shared
+        // parser and pull out just the name.
+        let mut found = false;
+        iter_directives(raw_directive, &mut |directive| {
+            ret.insert(directive.directive_name.to_owned());
+            found = true;
+        });
+        if !found {
+            bail!("failed to parse directive `{}`", raw_directive);
         }
-
-        // Now, let's extract the directive name.
-        let directive_name = if let Some((directive_name, _)) = rest.split_once([':', ' ']) {
-            directive_name.trim()
-        } else {
-            let directive_name = rest;
-            assert!(!directive_name.trim().contains([' ']));
-            directive_name.trim()
-        };
-
-        ret.insert(directive_name.to_owned());
     }
 
     Ok(ret)
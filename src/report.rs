@@ -0,0 +1,145 @@
+//! A thread-safe report accumulated while `migrate` rewrites test files in parallel, so
+//! maintainers can see at a glance which directives were actually touched and which
+//! directive-shaped comments were left alone because the collected directive set didn't
+//! recognize them.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A `//` line that was rewritten to `//@` because its whitespace-normalized content matched a
+/// collected header.
+#[derive(Debug)]
+pub(crate) struct RewrittenDirective {
+    pub(crate) path: PathBuf,
+    pub(crate) line_number: usize,
+    pub(crate) directive_name: String,
+}
+
+/// A `//`-prefixed comment that looks like a directive (i.e. its name is directive-shaped, per
+/// `looks_like_directive_name`), but whose whitespace-normalized line didn't match any collected
+/// header, so it was left untouched. Whether its name appears in the collected set is not checked.
+#[derive(Debug)]
+pub(crate) struct UnmatchedDirective {
+    pub(crate) path: PathBuf,
+    pub(crate) line_number: usize,
+    pub(crate) directive_name: String,
+}
+
+/// A `//[X]~...` error annotation whose bracketed revision `X` isn't declared by that file's
+/// `//@ revisions: ...` list.
+#[derive(Debug)]
+pub(crate) struct UndeclaredAnnotationRevision {
+    pub(crate) path: PathBuf,
+    pub(crate) line_number: usize,
+    pub(crate) revision: String,
+}
+
+/// Accumulates migration results across all files being processed in parallel.
+#[derive(Debug, Default)]
+pub(crate) struct MigrationReport {
+    rewritten_counts: Mutex<BTreeMap<String, u64>>,
+    rewritten_lines: Mutex<Vec<RewrittenDirective>>,
+    unmatched_directive_shaped: Mutex<Vec<UnmatchedDirective>>,
+    undeclared_annotation_revisions: Mutex<Vec<UndeclaredAnnotationRevision>>,
+}
+
+impl MigrationReport {
+    /// Records that one line was rewritten to `//@` for `directive_name`, both as an aggregate
+    /// per-directive count and as an individually reviewable entry (so a false-positive rewrite
+    /// that matched the collected set by mistake can be spotted and traced back to its line).
+    pub(crate) fn record_rewrite(&self, path: PathBuf, line_number: usize, directive_name: &str) {
+        *self
+            .rewritten_counts
+            .lock()
+            .unwrap()
+            .entry(directive_name.to_owned())
+            .or_insert(0) += 1;
+        self.rewritten_lines.lock().unwrap().push(RewrittenDirective {
+            path,
+            line_number,
+            directive_name: directive_name.to_owned(),
+        });
+    }
+
+    /// Records a directive-shaped comment that was left untouched because its line didn't match
+    /// any collected header.
+    pub(crate) fn record_unmatched(&self, path: PathBuf, line_number: usize, directive_name: &str) {
+        self.unmatched_directive_shaped.lock().unwrap().push(UnmatchedDirective {
+            path,
+            line_number,
+            directive_name: directive_name.to_owned(),
+        });
+    }
+
+    /// Records a `//[X]~...` annotation whose revision `X` isn't declared by that file.
+    pub(crate) fn record_undeclared_annotation_revision(
+        &self,
+        path: PathBuf,
+        line_number: usize,
+        revision: &str,
+    ) {
+        self.undeclared_annotation_revisions.lock().unwrap().push(UndeclaredAnnotationRevision {
+            path,
+            line_number,
+            revision: revision.to_owned(),
+        });
+    }
+
+    /// Prints a human-readable summary of the migration run.
+    pub(crate) fn print_summary(&self) {
+        let rewritten_counts = self.rewritten_counts.lock().unwrap();
+        println!("\nmigration report: rewrote {} distinct directive(s)", rewritten_counts.len());
+        for (directive_name, count) in rewritten_counts.iter() {
+            println!("  {directive_name}: {count} line(s) rewritten to `//@`");
+        }
+
+        let rewritten_lines = self.rewritten_lines.lock().unwrap();
+        if !rewritten_lines.is_empty() {
+            println!(
+                "\n{} line(s) rewritten to `//@` (review for false positives):",
+                rewritten_lines.len()
+            );
+            for entry in rewritten_lines.iter() {
+                println!(
+                    "  {}:{}: `{}`",
+                    entry.path.display(),
+                    entry.line_number,
+                    entry.directive_name
+                );
+            }
+        }
+
+        let unmatched = self.unmatched_directive_shaped.lock().unwrap();
+        if !unmatched.is_empty() {
+            println!(
+                "\n{} directive-shaped comment(s) were left untouched (line not in the collected set):",
+                unmatched.len()
+            );
+            for entry in unmatched.iter() {
+                println!(
+                    "  {}:{}: `{}`",
+                    entry.path.display(),
+                    entry.line_number,
+                    entry.directive_name
+                );
+            }
+        }
+
+        let undeclared = self.undeclared_annotation_revisions.lock().unwrap();
+        if !undeclared.is_empty() {
+            println!(
+                "\n{} error-annotation(s) reference a revision their file doesn't declare:",
+                undeclared.len()
+            );
+            for entry in undeclared.iter() {
+                println!(
+                    "  {}:{}: revision `{}`",
+                    entry.path.display(),
+                    entry.line_number,
+                    entry.revision
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Flags (and optionally deletes) expected-output files that reference a revision a test no
+//! longer declares, e.g. a leftover `foo.old-revision.stderr` after a `//@ revisions:` list was
+//! trimmed. Ported from the same idea as the `rustc` tidy check of the same name.
+
+use std::path::{Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::collect::compiletest_test_file_paths;
+use crate::directives::declared_revisions;
+
+const OUTPUT_EXTENSIONS: [&str; 2] = ["stderr", "stdout"];
+
+/// Whole-test `--compare-mode` suffixes compiletest itself recognizes. A
+/// `<base>.<mode>.{stderr,stdout}` output using one of these is a valid compare-mode output for
+/// the test as a whole, not a per-revision output, even on a test that happens to declare
+/// revisions, so it must never be treated as an orphaned revision output.
+const COMPARE_MODES: [&str; 5] =
+    ["polonius", "chalk", "next-solver", "split-dwarf", "split-dwarf-single-file"];
+
+/// An expected-output file whose filename references a revision the test no longer declares.
+#[derive(Debug)]
+pub(crate) struct OrphanedOutput {
+    pub(crate) path: PathBuf,
+    pub(crate) revision: String,
+    /// Whether the owning test file declares a `//@ revisions: ...` list at all. When it
+    /// doesn't, `revision` may just be a compare-mode suffix not listed in [`COMPARE_MODES`]
+    /// rather than a stale revision, so it isn't safe to delete.
+    revisions_declared: bool,
+}
+
+/// Returns the revision token a sibling expected-output filename encodes, if any.
+///
+/// Recognizes both `<base>.<revision>.<ext>` and
+/// `<base>.<revision>.<compare-mode>.{stderr,stdout}`. Returns `None` when the leading token is a
+/// known [`COMPARE_MODES`] suffix rather than a revision, e.g. `<base>.next-solver.stderr`, since
+/// those are shaped identically to a one-revision output but aren't one.
+fn output_revision(file_name: &str, base_name: &str) -> Option<String> {
+    let ext = OUTPUT_EXTENSIONS
+        .iter()
+        .find(|ext| file_name.ends_with(&format!(".{ext}")))?;
+    let without_ext = file_name.strip_suffix(&format!(".{ext}"))?;
+    let middle = without_ext.strip_prefix(base_name)?.strip_prefix('.')?;
+    // `middle` is either `<revision>` or `<revision>.<compare-mode>`.
+    let revision = middle.split('.').next()?;
+    (!revision.is_empty() && !COMPARE_MODES.contains(&revision)).then(|| revision.to_owned())
+}
+
+/// Scans every test file under `path_to_rustc/tests` for sibling `.stderr`/`.stdout` files whose
+/// filename references a revision the test no longer declares, optionally deleting them.
+pub(crate) fn check_stale_outputs(path_to_rustc: &Path, fix: bool) -> anyhow::Result<()> {
+    let test_file_paths = compiletest_test_file_paths(path_to_rustc, false);
+
+    let mut orphaned = Vec::new();
+
+    for test_path in &test_file_paths {
+        let Some(parent) = test_path.parent() else {
+            continue;
+        };
+        let Some(base_name) = test_path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        let contents = std::fs::read_to_string(test_path)?;
+        let revisions = declared_revisions(&contents);
+
+        let Ok(siblings) = std::fs::read_dir(parent) else {
+            continue;
+        };
+        for sibling in siblings.filter_map(Result::ok) {
+            let sibling_path = sibling.path();
+            let Some(file_name) = sibling_path.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            let Some(revision) = output_revision(file_name, base_name) else {
+                continue;
+            };
+
+            if !revisions.contains(&revision) {
+                orphaned.push(OrphanedOutput {
+                    path: sibling_path,
+                    revision,
+                    revisions_declared: !revisions.is_empty(),
+                });
+            }
+        }
+    }
+
+    let mut deleted = 0;
+    for output in &orphaned {
+        warn!(
+            path = ?output.path,
+            revision = %output.revision,
+            "orphaned expected-output file references an undeclared revision"
+        );
+        if fix {
+            if output.revisions_declared {
+                std::fs::remove_file(&output.path)?;
+                deleted += 1;
+            } else {
+                warn!(
+                    path = ?output.path,
+                    "not deleting: test declares no revisions, so this may be a compare-mode \
+                     output rather than a stale revision output"
+                );
+            }
+        }
+    }
+
+    info!(
+        "found {} orphaned expected-output file(s){}",
+        orphaned.len(),
+        if fix { format!(", deleted {deleted}") } else { String::new() }
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_single_revision_outputs() {
+        assert_eq!(output_revision("foo.bar.stderr", "foo"), Some("bar".to_owned()));
+        assert_eq!(output_revision("foo.bar.stdout", "foo"), Some("bar".to_owned()));
+    }
+
+    #[test]
+    fn recognizes_revision_plus_compare_mode_outputs() {
+        assert_eq!(output_revision("foo.bar.polonius.stderr", "foo"), Some("bar".to_owned()));
+    }
+
+    #[test]
+    fn treats_whole_test_compare_mode_outputs_as_not_a_revision() {
+        for mode in COMPARE_MODES {
+            assert_eq!(output_revision(&format!("foo.{mode}.stderr"), "foo"), None, "{mode}");
+        }
+    }
+
+    #[test]
+    fn returns_none_for_unrelated_filenames() {
+        assert_eq!(output_revision("foo.stderr", "foo"), None);
+        assert_eq!(output_revision("bar.baz.stderr", "foo"), None);
+        assert_eq!(output_revision("foo.rs", "foo"), None);
+    }
+}